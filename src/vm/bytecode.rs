@@ -0,0 +1,210 @@
+//! Instruction and operand types shared by the VM, its loader, and its REPL.
+
+/// The set of opcodes the `VirtualMachine` understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteCode {
+    HALT,
+    LOADC,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    AND,
+    OR,
+    EQ,
+    NEQ,
+    LE,
+    LEQ,
+    GE,
+    GEQ,
+    NEG,
+    NOT,
+    JMP,
+    JMPZ,
+    JMPNZ,
+    CALL,
+    RET,
+    LOAD,
+    STORE,
+}
+
+/// A single instruction: an opcode plus an optional immediate operand.
+///
+/// Most data-carrying opcodes (`LOADC`, `JMP`/`JMPZ`/`JMPNZ`, `LOAD`/`STORE`)
+/// populate only `data`. `CALL` additionally populates `argc` with the
+/// number of arguments to move from the operand stack into the new frame.
+#[derive(Debug, Clone)]
+pub struct Inst<T> {
+    pub inst: ByteCode,
+    pub data: Option<T>,
+    pub argc: Option<usize>,
+}
+
+impl<T> Inst<T> {
+    pub fn new_inst(inst: ByteCode) -> Inst<T> {
+        Inst {
+            inst,
+            data: None,
+            argc: None,
+        }
+    }
+
+    pub fn new_data(inst: ByteCode, data: T) -> Inst<T> {
+        Inst {
+            inst,
+            data: Some(data),
+            argc: None,
+        }
+    }
+
+    /// Build a `CALL` to `target` passing `argc` arguments from the operand stack.
+    pub fn new_call(target: T, argc: usize) -> Inst<T> {
+        Inst {
+            inst: ByteCode::CALL,
+            data: Some(target),
+            argc: Some(argc),
+        }
+    }
+}
+
+impl ByteCode {
+    /// The single byte used to represent this opcode in a bytecode image.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ByteCode::HALT => 0x00,
+            ByteCode::LOADC => 0x01,
+            ByteCode::ADD => 0x02,
+            ByteCode::SUB => 0x03,
+            ByteCode::MUL => 0x04,
+            ByteCode::DIV => 0x05,
+            ByteCode::MOD => 0x06,
+            ByteCode::AND => 0x07,
+            ByteCode::OR => 0x08,
+            ByteCode::EQ => 0x09,
+            ByteCode::NEQ => 0x0a,
+            ByteCode::LE => 0x0b,
+            ByteCode::LEQ => 0x0c,
+            ByteCode::GE => 0x0d,
+            ByteCode::GEQ => 0x0e,
+            ByteCode::NEG => 0x0f,
+            ByteCode::NOT => 0x10,
+            ByteCode::JMP => 0x11,
+            ByteCode::JMPZ => 0x12,
+            ByteCode::JMPNZ => 0x13,
+            ByteCode::CALL => 0x14,
+            ByteCode::RET => 0x15,
+            ByteCode::LOAD => 0x16,
+            ByteCode::STORE => 0x17,
+        }
+    }
+
+    /// Decode an opcode byte, returning `None` for an unknown opcode.
+    pub fn from_byte(byte: u8) -> Option<ByteCode> {
+        match byte {
+            0x00 => Some(ByteCode::HALT),
+            0x01 => Some(ByteCode::LOADC),
+            0x02 => Some(ByteCode::ADD),
+            0x03 => Some(ByteCode::SUB),
+            0x04 => Some(ByteCode::MUL),
+            0x05 => Some(ByteCode::DIV),
+            0x06 => Some(ByteCode::MOD),
+            0x07 => Some(ByteCode::AND),
+            0x08 => Some(ByteCode::OR),
+            0x09 => Some(ByteCode::EQ),
+            0x0a => Some(ByteCode::NEQ),
+            0x0b => Some(ByteCode::LE),
+            0x0c => Some(ByteCode::LEQ),
+            0x0d => Some(ByteCode::GE),
+            0x0e => Some(ByteCode::GEQ),
+            0x0f => Some(ByteCode::NEG),
+            0x10 => Some(ByteCode::NOT),
+            0x11 => Some(ByteCode::JMP),
+            0x12 => Some(ByteCode::JMPZ),
+            0x13 => Some(ByteCode::JMPNZ),
+            0x14 => Some(ByteCode::CALL),
+            0x15 => Some(ByteCode::RET),
+            0x16 => Some(ByteCode::LOAD),
+            0x17 => Some(ByteCode::STORE),
+            _ => None,
+        }
+    }
+
+    /// Whether this opcode carries an immediate operand in the instruction stream.
+    pub fn has_operand(self) -> bool {
+        matches!(
+            self,
+            ByteCode::LOADC
+                | ByteCode::JMP
+                | ByteCode::JMPZ
+                | ByteCode::JMPNZ
+                | ByteCode::CALL
+                | ByteCode::LOAD
+                | ByteCode::STORE
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_roundtrip_covers_every_opcode() {
+        let opcodes = [
+            ByteCode::HALT,
+            ByteCode::LOADC,
+            ByteCode::ADD,
+            ByteCode::SUB,
+            ByteCode::MUL,
+            ByteCode::DIV,
+            ByteCode::MOD,
+            ByteCode::AND,
+            ByteCode::OR,
+            ByteCode::EQ,
+            ByteCode::NEQ,
+            ByteCode::LE,
+            ByteCode::LEQ,
+            ByteCode::GE,
+            ByteCode::GEQ,
+            ByteCode::NEG,
+            ByteCode::NOT,
+            ByteCode::JMP,
+            ByteCode::JMPZ,
+            ByteCode::JMPNZ,
+            ByteCode::CALL,
+            ByteCode::RET,
+            ByteCode::LOAD,
+            ByteCode::STORE,
+        ];
+        for op in opcodes {
+            assert_eq!(ByteCode::from_byte(op.to_byte()), Some(op));
+        }
+    }
+
+    #[test]
+    fn from_byte_rejects_unknown_opcode() {
+        assert_eq!(ByteCode::from_byte(0xff), None);
+    }
+
+    #[test]
+    fn only_data_carrying_opcodes_carry_an_operand() {
+        assert!(ByteCode::LOADC.has_operand());
+        assert!(ByteCode::JMP.has_operand());
+        assert!(ByteCode::JMPZ.has_operand());
+        assert!(ByteCode::JMPNZ.has_operand());
+        assert!(ByteCode::CALL.has_operand());
+        assert!(ByteCode::LOAD.has_operand());
+        assert!(ByteCode::STORE.has_operand());
+        assert!(!ByteCode::RET.has_operand());
+        assert!(!ByteCode::ADD.has_operand());
+    }
+
+    #[test]
+    fn new_call_carries_target_and_argc() {
+        let call: Inst<i64> = Inst::new_call(42, 2);
+        assert_eq!(call.inst, ByteCode::CALL);
+        assert_eq!(call.data, Some(42));
+        assert_eq!(call.argc, Some(2));
+    }
+}
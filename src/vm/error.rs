@@ -0,0 +1,47 @@
+//! The trap type raised by `VirtualMachine::run` and friends.
+
+use std::error::Error;
+use std::fmt;
+
+/// A trap raised during execution of an `Inst<T>` program.
+///
+/// Every variant that can occur mid-program carries the `pc` of the
+/// instruction that raised it, so callers can report position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    StackUnderflow { pc: usize },
+    DivideByZero { pc: usize },
+    ModByZero { pc: usize },
+    NegationOverflow { pc: usize },
+    InvalidProgramCounter { pc: usize },
+    InvalidLocalSlot { pc: usize },
+    MissingArgc { pc: usize },
+    BudgetExhausted { pc: usize },
+    UnexpectedEndOfProgram,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::StackUnderflow { pc } => write!(f, "stack underflow at pc {}", pc),
+            VmError::DivideByZero { pc } => write!(f, "division by zero at pc {}", pc),
+            VmError::ModByZero { pc } => write!(f, "modulo by zero at pc {}", pc),
+            VmError::NegationOverflow { pc } => write!(f, "negation overflowed at pc {}", pc),
+            VmError::InvalidProgramCounter { pc } => {
+                write!(f, "invalid control transfer at pc {}", pc)
+            }
+            VmError::InvalidLocalSlot { pc } => write!(f, "local slot out of range at pc {}", pc),
+            VmError::MissingArgc { pc } => {
+                write!(f, "CALL at pc {} has no argc", pc)
+            }
+            VmError::BudgetExhausted { pc } => {
+                write!(f, "instruction budget exhausted at pc {}", pc)
+            }
+            VmError::UnexpectedEndOfProgram => {
+                write!(f, "ran off the end of the program without a HALT")
+            }
+        }
+    }
+}
+
+impl Error for VmError {}
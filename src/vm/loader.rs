@@ -0,0 +1,275 @@
+//! Binary (de)serialization of `Inst<T>` programs, so they can be compiled
+//! ahead-of-time and shipped as files instead of built with `Inst::new_inst`/
+//! `new_data` at runtime.
+//!
+//! # Image format
+//!
+//! ```text
+//! magic:   4 bytes, b"ESTA"
+//! version: 1 byte
+//! width:   1 byte, tag for the operand encoding (see `OperandWidth`)
+//! records: one per instruction, each an opcode byte followed by the
+//!          operand bytes if (and only if) the opcode carries one,
+//!          followed by one further argc byte if the opcode is `CALL`
+//! ```
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::bytecode::{ByteCode, Inst};
+
+const MAGIC: [u8; 4] = *b"ESTA";
+const VERSION: u8 = 1;
+
+/// Failure modes when decoding a bytecode image.
+#[derive(Debug)]
+pub enum LoadError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownOperandWidth(u8),
+    UnknownOpcode(u8),
+    MissingOperand(ByteCode),
+    MissingArgc,
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::BadMagic => write!(f, "not an esta bytecode image"),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported image version {}", v),
+            LoadError::UnknownOperandWidth(w) => write!(f, "unknown operand width tag {}", w),
+            LoadError::UnknownOpcode(b) => write!(f, "unknown opcode byte 0x{:02x}", b),
+            LoadError::MissingOperand(op) => {
+                write!(f, "{:?} requires an operand but none was found", op)
+            }
+            LoadError::MissingArgc => write!(f, "CALL requires an argc byte but none was found"),
+            LoadError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+/// Failure modes when encoding a bytecode image.
+#[derive(Debug)]
+pub enum DumpError {
+    /// A `CALL`'s `argc` doesn't fit in the image format's one-byte field.
+    ArgcTooLarge(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DumpError::ArgcTooLarge(argc) => {
+                write!(f, "CALL argc {} does not fit in one byte", argc)
+            }
+            DumpError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> DumpError {
+        DumpError::Io(e)
+    }
+}
+
+/// Tag identifying how wide (and how to encode/decode) `T`'s operand bytes are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandWidth {
+    I64 = 0x08,
+}
+
+impl OperandWidth {
+    fn from_tag(tag: u8) -> Result<OperandWidth, LoadError> {
+        match tag {
+            0x08 => Ok(OperandWidth::I64),
+            _ => Err(LoadError::UnknownOperandWidth(tag)),
+        }
+    }
+}
+
+/// Types that can appear as an `Inst<T>` operand in a bytecode image.
+///
+/// Only implemented for the operand encodings the loader currently supports.
+pub trait Operand: Sized {
+    const WIDTH: OperandWidth;
+
+    fn to_le_bytes(&self) -> Vec<u8>;
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+impl Operand for i64 {
+    const WIDTH: OperandWidth = OperandWidth::I64;
+
+    fn to_le_bytes(&self) -> Vec<u8> {
+        i64::to_le_bytes(*self).to_vec()
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+        i64::from_le_bytes(buf)
+    }
+}
+
+/// Parse a compact binary program image into a sequence of instructions.
+pub fn load<T: Operand>(reader: &mut impl Read) -> Result<Vec<Inst<T>>, LoadError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let [version, width_tag] = header;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let width = OperandWidth::from_tag(width_tag)?;
+    if width != T::WIDTH {
+        return Err(LoadError::UnknownOperandWidth(width_tag));
+    }
+
+    let mut prog = Vec::new();
+    let mut opcode_byte = [0u8; 1];
+    loop {
+        let n = reader.read(&mut opcode_byte)?;
+        if n == 0 {
+            break;
+        }
+        let opcode =
+            ByteCode::from_byte(opcode_byte[0]).ok_or(LoadError::UnknownOpcode(opcode_byte[0]))?;
+
+        if opcode.has_operand() {
+            let width_bytes = match T::WIDTH {
+                OperandWidth::I64 => 8,
+            };
+            let mut operand = vec![0u8; width_bytes];
+            reader
+                .read_exact(&mut operand)
+                .map_err(|_| LoadError::MissingOperand(opcode))?;
+            let data = T::from_le_bytes(&operand);
+
+            if opcode == ByteCode::CALL {
+                let mut argc = [0u8; 1];
+                reader.read_exact(&mut argc).map_err(|_| LoadError::MissingArgc)?;
+                prog.push(Inst::new_call(data, argc[0] as usize));
+            } else {
+                prog.push(Inst::new_data(opcode, data));
+            }
+        } else {
+            prog.push(Inst::new_inst(opcode));
+        }
+    }
+
+    Ok(prog)
+}
+
+/// Serialize a program back into the binary image format read by `load`.
+pub fn dump<T: Operand>(prog: &[Inst<T>], writer: &mut impl Write) -> Result<(), DumpError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION, T::WIDTH as u8])?;
+
+    for inst in prog {
+        writer.write_all(&[inst.inst.to_byte()])?;
+        if let Some(data) = &inst.data {
+            writer.write_all(&data.to_le_bytes())?;
+        }
+        if inst.inst == ByteCode::CALL {
+            let argc = inst.argc.unwrap_or(0);
+            let argc_byte = u8::try_from(argc).map_err(|_| DumpError::ArgcTooLarge(argc))?;
+            writer.write_all(&[argc_byte])?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrips_a_program() {
+        let prog: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 2),
+            Inst::new_data(ByteCode::LOADC, 3),
+            Inst::new_inst(ByteCode::ADD),
+            Inst::new_inst(ByteCode::HALT),
+        ];
+
+        let mut bytes = Vec::new();
+        dump(&prog, &mut bytes).unwrap();
+
+        let loaded: Vec<Inst<i64>> = load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.len(), prog.len());
+        for (a, b) in loaded.iter().zip(prog.iter()) {
+            assert_eq!(a.inst, b.inst);
+            assert_eq!(a.data, b.data);
+        }
+    }
+
+    #[test]
+    fn roundtrips_a_call() {
+        let prog: Vec<Inst<i64>> = vec![Inst::new_call(5, 2), Inst::new_inst(ByteCode::RET)];
+
+        let mut bytes = Vec::new();
+        dump(&prog, &mut bytes).unwrap();
+
+        let loaded: Vec<Inst<i64>> = load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded[0].inst, ByteCode::CALL);
+        assert_eq!(loaded[0].data, Some(5));
+        assert_eq!(loaded[0].argc, Some(2));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let bytes = [0u8; 8];
+        let res: Result<Vec<Inst<i64>>, LoadError> = load(&mut &bytes[..]);
+        assert!(matches!(res, Err(LoadError::BadMagic)));
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(OperandWidth::I64 as u8);
+        bytes.push(0xff);
+        let res: Result<Vec<Inst<i64>>, LoadError> = load(&mut bytes.as_slice());
+        assert!(matches!(res, Err(LoadError::UnknownOpcode(0xff))));
+    }
+
+    #[test]
+    fn rejects_truncated_operand() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(OperandWidth::I64 as u8);
+        bytes.push(ByteCode::LOADC.to_byte());
+        bytes.extend_from_slice(&[0u8; 3]); // short by 5 bytes
+        let res: Result<Vec<Inst<i64>>, LoadError> = load(&mut bytes.as_slice());
+        assert!(matches!(res, Err(LoadError::MissingOperand(ByteCode::LOADC))));
+    }
+
+    #[test]
+    fn rejects_an_argc_that_does_not_fit_in_a_byte() {
+        let prog: Vec<Inst<i64>> = vec![Inst::new_call(5, 256)];
+
+        let mut bytes = Vec::new();
+        let res = dump(&prog, &mut bytes);
+        assert!(matches!(res, Err(DumpError::ArgcTooLarge(256))));
+    }
+}
@@ -1,11 +1,26 @@
 pub mod bytecode;
+pub mod error;
+pub mod loader;
+pub mod repl;
 
 extern crate num_traits;
 
 use self::bytecode::{ByteCode, Inst};
-use num_traits::{CheckedNeg, Num, One, Zero};
+pub use self::error::VmError;
+use num_traits::{CheckedNeg, Num, One, ToPrimitive, Zero};
 use std::cmp::PartialOrd;
 
+/// A callback invoked periodically by `run`; returns `true` to request a halt.
+type TimerHandler<T> = Box<dyn FnMut(&VirtualMachine<T>) -> bool>;
+
+/// Whether `VirtualMachine::step` executed an instruction or hit a `HALT`
+/// (or a timer callback that requested one).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,
+    Halted,
+}
+
 /// The Esta Virtual Machine
 #[allow(dead_code)]
 pub struct VirtualMachine<T> {
@@ -14,10 +29,22 @@ pub struct VirtualMachine<T> {
     inst: Vec<Inst<T>>,
     data: T,
     pc: usize,
+    /// Base of the current frame's locals within `mem`.
+    fp: usize,
+    /// One (return_pc, saved_fp) entry per active call.
+    frames: Vec<(usize, usize)>,
+    /// Steps taken so far, counting a step stopped by the budget or a timer halt.
+    cycles: u64,
+    /// Trap with `VmError::BudgetExhausted` once `cycles` exceeds this.
+    budget: Option<u64>,
+    /// Wrap-around counter driving the periodic timer callback.
+    timer: u64,
+    timer_period: Option<u64>,
+    timer_handler: Option<TimerHandler<T>>,
 }
 
 #[allow(dead_code)]
-impl<T: Num + Clone + PartialOrd + CheckedNeg> VirtualMachine<T> {
+impl<T: Num + Clone + PartialOrd + CheckedNeg + ToPrimitive> VirtualMachine<T> {
     pub fn new(inst: Vec<Inst<T>>) -> VirtualMachine<T> {
         VirtualMachine {
             stack: Vec::new(),
@@ -25,80 +52,196 @@ impl<T: Num + Clone + PartialOrd + CheckedNeg> VirtualMachine<T> {
             inst,
             data: Zero::zero(),
             pc: 0,
+            fp: 0,
+            frames: Vec::new(),
+            cycles: 0,
+            budget: None,
+            timer: 0,
+            timer_period: None,
+            timer_handler: None,
         }
     }
 
-    pub fn run(&mut self) -> Result<(), &'static str> {
+    /// Let at most `budget` instructions run; the step after that traps with
+    /// `VmError::BudgetExhausted` without running its instruction.
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = Some(budget);
+    }
+
+    /// Number of steps taken so far, including a step that trapped on the
+    /// budget or was halted by the timer before its instruction ran.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Call `handler` every `period` cycles; if it returns `true`, execution
+    /// halts as if a `HALT` had been reached.
+    pub fn set_timer<F>(&mut self, period: u64, handler: F)
+    where
+        F: FnMut(&VirtualMachine<T>) -> bool + 'static,
+    {
+        self.timer_period = Some(period);
+        self.timer_handler = Some(Box::new(handler));
+    }
+
+    /// Run until `HALT`, a timer callback requests a halt, or a trap occurs.
+    pub fn run(&mut self) -> Result<(), VmError> {
         loop {
-            let ir = &self.inst[self.pc];
-            self.pc += 1;
-            match ir.inst {
-                ByteCode::HALT => return Ok(()),
-                ByteCode::LOADC => self.push(ir.data.clone().unwrap()),
-                ByteCode::ADD => {
-                    let res = self.pop()? + self.pop()?;
-                    self.push(res);
-                }
-                ByteCode::SUB => {
-                    let res = self.pop()? - self.pop()?;
-                    self.push(res);
-                }
-                ByteCode::MUL => {
-                    let res = self.pop()? * self.pop()?;
-                    self.push(res);
-                }
-                ByteCode::DIV => {
-                    let res = self.pop()? / self.pop()?;
-                    self.push(res);
-                }
-                ByteCode::MOD => {
-                    let res = self.pop()? % self.pop()?;
-                    self.push(res);
-                }
-                ByteCode::AND => {
-                    let lhs = VirtualMachine::t_to_bool(self.pop()?);
-                    let rhs = VirtualMachine::t_to_bool(self.pop()?);
-                    self.push(VirtualMachine::bool_to_t(lhs && rhs));
-                }
-                ByteCode::OR => {
-                    let lhs = VirtualMachine::t_to_bool(self.pop()?);
-                    let rhs = VirtualMachine::t_to_bool(self.pop()?);
-                    self.push(VirtualMachine::bool_to_t(lhs || rhs));
-                }
-                ByteCode::EQ => {
-                    let res = self.pop()? == self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
-                }
-                ByteCode::NEQ => {
-                    let res = self.pop()? != self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
-                }
-                ByteCode::LE => {
-                    let res = self.pop()? < self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
-                }
-                ByteCode::LEQ => {
-                    let res = self.pop()? <= self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
+            if self.step()? == StepOutcome::Halted {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute one instruction, returning whether it halted the machine. When
+    /// a timer callback requests a halt, `Halted` is returned before the
+    /// instruction at `pc` runs.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        let pc = self.pc;
+        if pc >= self.inst.len() {
+            return Err(VmError::UnexpectedEndOfProgram);
+        }
+
+        self.cycles += 1;
+        if let Some(budget) = self.budget {
+            if self.cycles > budget {
+                return Err(VmError::BudgetExhausted { pc });
+            }
+        }
+
+        self.timer = self.timer.wrapping_add(1);
+        if let Some(period) = self.timer_period {
+            if period != 0 && self.timer.is_multiple_of(period) {
+                if let Some(mut handler) = self.timer_handler.take() {
+                    let should_halt = handler(self);
+                    self.timer_handler = Some(handler);
+                    if should_halt {
+                        return Ok(StepOutcome::Halted);
+                    }
                 }
-                ByteCode::GE => {
-                    let res = self.pop()? < self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
+            }
+        }
+        let ir = &self.inst[pc];
+        self.pc += 1;
+        match ir.inst {
+            ByteCode::HALT => return Ok(StepOutcome::Halted),
+            ByteCode::LOADC => self.push(ir.data.clone().unwrap()),
+            ByteCode::ADD => {
+                let res = self.pop(pc)? + self.pop(pc)?;
+                self.push(res);
+            }
+            ByteCode::SUB => {
+                let res = self.pop(pc)? - self.pop(pc)?;
+                self.push(res);
+            }
+            ByteCode::MUL => {
+                let res = self.pop(pc)? * self.pop(pc)?;
+                self.push(res);
+            }
+            ByteCode::DIV => {
+                let lhs = self.pop(pc)?;
+                let rhs = self.pop(pc)?;
+                if rhs.is_zero() {
+                    return Err(VmError::DivideByZero { pc });
                 }
-                ByteCode::GEQ => {
-                    let res = self.pop()? <= self.pop()?;
-                    self.push(VirtualMachine::bool_to_t(res));
+                self.push(lhs / rhs);
+            }
+            ByteCode::MOD => {
+                let lhs = self.pop(pc)?;
+                let rhs = self.pop(pc)?;
+                if rhs.is_zero() {
+                    return Err(VmError::ModByZero { pc });
                 }
-                ByteCode::NEG => {
-                    let res = self.pop()?.checked_neg().unwrap();
-                    self.push(res);
+                self.push(lhs % rhs);
+            }
+            ByteCode::AND => {
+                let lhs = VirtualMachine::t_to_bool(self.pop(pc)?);
+                let rhs = VirtualMachine::t_to_bool(self.pop(pc)?);
+                self.push(VirtualMachine::bool_to_t(lhs && rhs));
+            }
+            ByteCode::OR => {
+                let lhs = VirtualMachine::t_to_bool(self.pop(pc)?);
+                let rhs = VirtualMachine::t_to_bool(self.pop(pc)?);
+                self.push(VirtualMachine::bool_to_t(lhs || rhs));
+            }
+            ByteCode::EQ => {
+                let res = self.pop(pc)? == self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::NEQ => {
+                let res = self.pop(pc)? != self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::LE => {
+                let res = self.pop(pc)? < self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::LEQ => {
+                let res = self.pop(pc)? <= self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::GE => {
+                let res = self.pop(pc)? < self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::GEQ => {
+                let res = self.pop(pc)? <= self.pop(pc)?;
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::NEG => {
+                let res = self
+                    .pop(pc)?
+                    .checked_neg()
+                    .ok_or(VmError::NegationOverflow { pc })?;
+                self.push(res);
+            }
+            ByteCode::NOT => {
+                let res = !VirtualMachine::t_to_bool(self.pop(pc)?);
+                self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::JMP => {
+                let target = VirtualMachine::<T>::operand_usize(&ir.data, pc)?;
+                self.jump_to(target, pc)?;
+            }
+            ByteCode::JMPZ => {
+                let data = ir.data.clone();
+                let cond = self.pop(pc)?;
+                if cond.is_zero() {
+                    let target = VirtualMachine::<T>::operand_usize(&data, pc)?;
+                    self.jump_to(target, pc)?;
                 }
-                ByteCode::NOT => {
-                    let res = !VirtualMachine::t_to_bool(self.pop()?);
-                    self.push(VirtualMachine::bool_to_t(res));
+            }
+            ByteCode::JMPNZ => {
+                let data = ir.data.clone();
+                let cond = self.pop(pc)?;
+                if !cond.is_zero() {
+                    let target = VirtualMachine::<T>::operand_usize(&data, pc)?;
+                    self.jump_to(target, pc)?;
                 }
             }
+            ByteCode::CALL => {
+                let target = VirtualMachine::<T>::operand_usize(&ir.data, pc)?;
+                let argc = ir.argc.ok_or(VmError::MissingArgc { pc })?;
+                self.call(target, argc, pc)?;
+            }
+            ByteCode::RET => {
+                self.ret(pc)?;
+            }
+            ByteCode::LOAD => {
+                let slot = VirtualMachine::<T>::operand_usize(&ir.data, pc)
+                    .map_err(|_| VmError::InvalidLocalSlot { pc })?;
+                let value = self.load_local(slot, pc)?;
+                self.push(value);
+            }
+            ByteCode::STORE => {
+                let slot = VirtualMachine::<T>::operand_usize(&ir.data, pc)
+                    .map_err(|_| VmError::InvalidLocalSlot { pc })?;
+                let value = self.pop(pc)?;
+                self.store_local(slot, value);
+            }
         }
+
+        Ok(StepOutcome::Continue)
     }
 
     #[inline]
@@ -106,14 +249,81 @@ impl<T: Num + Clone + PartialOrd + CheckedNeg> VirtualMachine<T> {
         self.stack.push(data);
     }
 
+    /// Decode a `usize`-valued operand (a jump target or a local slot).
+    fn operand_usize(data: &Option<T>, pc: usize) -> Result<usize, VmError> {
+        data.clone()
+            .and_then(|t| t.to_usize())
+            .ok_or(VmError::InvalidProgramCounter { pc })
+    }
+
+    /// Set `self.pc` to `target`, trapping if it falls outside the program.
+    fn jump_to(&mut self, target: usize, pc: usize) -> Result<(), VmError> {
+        if target >= self.inst.len() {
+            return Err(VmError::InvalidProgramCounter { pc });
+        }
+        self.pc = target;
+        Ok(())
+    }
+
+    /// Move `argc` arguments from the operand stack into a new frame in
+    /// `mem` and transfer control to `target`.
+    fn call(&mut self, target: usize, argc: usize, pc: usize) -> Result<(), VmError> {
+        if target >= self.inst.len() {
+            return Err(VmError::InvalidProgramCounter { pc });
+        }
+        if self.stack.len() < argc {
+            return Err(VmError::StackUnderflow { pc });
+        }
+
+        let args = self.stack.split_off(self.stack.len() - argc);
+        self.frames.push((self.pc, self.fp));
+        self.fp = self.mem.len();
+        self.mem.extend(args);
+        self.pc = target;
+        Ok(())
+    }
+
+    /// Tear down the current frame, restoring `pc` and `fp`, and leave the
+    /// return value on top of the operand stack.
+    fn ret(&mut self, pc: usize) -> Result<(), VmError> {
+        let result = self.pop(pc)?;
+        let (return_pc, saved_fp) = self
+            .frames
+            .pop()
+            .ok_or(VmError::InvalidProgramCounter { pc })?;
+        self.mem.truncate(self.fp);
+        self.fp = saved_fp;
+        self.pc = return_pc;
+        self.push(result);
+        Ok(())
+    }
+
+    /// Read local slot `slot` relative to the current frame base.
+    fn load_local(&self, slot: usize, pc: usize) -> Result<T, VmError> {
+        self.mem
+            .get(self.fp + slot)
+            .cloned()
+            .ok_or(VmError::InvalidLocalSlot { pc })
+    }
+
+    /// Write local slot `slot` relative to the current frame base, growing
+    /// `mem` with zeroes if the slot doesn't exist yet.
+    fn store_local(&mut self, slot: usize, value: T) {
+        let index = self.fp + slot;
+        if index >= self.mem.len() {
+            self.mem.resize(index + 1, Zero::zero());
+        }
+        self.mem[index] = value;
+    }
+
     #[inline]
-    fn top(&mut self) -> Result<&T, &'static str> {
-        self.stack.last().ok_or_else(|| "Empty stack")
+    fn top(&mut self, pc: usize) -> Result<&T, VmError> {
+        self.stack.last().ok_or(VmError::StackUnderflow { pc })
     }
 
     #[inline]
-    fn pop(&mut self) -> Result<T, &'static str> {
-        self.data = self.top()?.clone();
+    fn pop(&mut self, pc: usize) -> Result<T, VmError> {
+        self.data = self.top(pc)?.clone();
         self.stack.pop();
         Ok(self.data.clone())
     }
@@ -122,6 +332,46 @@ impl<T: Num + Clone + PartialOrd + CheckedNeg> VirtualMachine<T> {
         &self.stack
     }
 
+    /// The current contents of `mem` (every frame's locals, not just the
+    /// active one), for inspection by the REPL.
+    pub fn debug_mem(&self) -> &Vec<T> {
+        &self.mem
+    }
+
+    /// The active call frames, as (return_pc, saved_fp) pairs.
+    pub fn debug_frames(&self) -> &Vec<(usize, usize)> {
+        &self.frames
+    }
+
+    /// The program counter of the next instruction to execute.
+    pub fn debug_pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The base of the current frame's locals within `mem`.
+    pub fn debug_fp(&self) -> usize {
+        self.fp
+    }
+
+    /// Append an instruction to the end of the loaded program, for a REPL
+    /// that grows the program one instruction at a time.
+    pub fn push_inst(&mut self, inst: Inst<T>) {
+        self.inst.push(inst);
+    }
+
+    /// Rewind to the start of the program, discarding the stack, locals,
+    /// call frames, and cycle/timer counters built up while running. The
+    /// loaded program, budget, and timer configuration are kept.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.mem.clear();
+        self.pc = 0;
+        self.fp = 0;
+        self.frames.clear();
+        self.cycles = 0;
+        self.timer = 0;
+    }
+
     fn bool_to_t(cond: bool) -> T {
         match cond {
             true => One::one(),
@@ -310,4 +560,213 @@ mod tests {
         assert_eq!(vm.run().is_ok(), true);
         assert_eq!(&[0].to_vec(), vm.debug_stack());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stack_underflow() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_inst(ByteCode::ADD)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::StackUnderflow { pc: 0 }));
+    }
+
+    #[test]
+    fn test_div_by_zero() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 0),
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::DIV),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::DivideByZero { pc: 2 }));
+    }
+
+    #[test]
+    fn test_mod_by_zero() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 0),
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::MOD),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::ModByZero { pc: 2 }));
+    }
+
+    #[test]
+    fn test_neg_overflow() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, i64::MIN),
+            Inst::new_inst(ByteCode::NEG),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::NegationOverflow { pc: 1 }));
+    }
+
+    #[test]
+    fn test_unexpected_end_of_program() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::LOADC, 1)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::UnexpectedEndOfProgram));
+    }
+
+    #[test]
+    fn test_jmp() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::JMP, 3),
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::HALT),
+            Inst::new_data(ByteCode::LOADC, 2),
+            Inst::new_inst(ByteCode::HALT),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run().is_ok(), true);
+        assert_eq!(&[2].to_vec(), vm.debug_stack());
+    }
+
+    #[test]
+    fn test_jmpz_takes_branch_when_top_is_zero() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 0),
+            Inst::new_data(ByteCode::JMPZ, 4),
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::HALT),
+            Inst::new_data(ByteCode::LOADC, 2),
+            Inst::new_inst(ByteCode::HALT),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run().is_ok(), true);
+        assert_eq!(&[2].to_vec(), vm.debug_stack());
+    }
+
+    #[test]
+    fn test_jmpnz_falls_through_when_top_is_zero() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 0),
+            Inst::new_data(ByteCode::JMPNZ, 4),
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::HALT),
+            Inst::new_data(ByteCode::LOADC, 2),
+            Inst::new_inst(ByteCode::HALT),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run().is_ok(), true);
+        assert_eq!(&[1].to_vec(), vm.debug_stack());
+    }
+
+    #[test]
+    fn test_jmp_out_of_range_traps() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::JMP, 99)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::InvalidProgramCounter { pc: 0 }));
+    }
+
+    #[test]
+    fn test_store_and_load_local_grows_mem() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 9),
+            Inst::new_data(ByteCode::STORE, 2),
+            Inst::new_data(ByteCode::LOAD, 2),
+            Inst::new_inst(ByteCode::HALT),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run().is_ok(), true);
+        assert_eq!(&[9].to_vec(), vm.debug_stack());
+    }
+
+    #[test]
+    fn test_load_out_of_range_local_traps() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::LOAD, 0)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::InvalidLocalSlot { pc: 0 }));
+    }
+
+    #[test]
+    fn test_call_ret_with_locals() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 3),
+            Inst::new_data(ByteCode::LOADC, 4),
+            Inst::new_call(4, 2),
+            Inst::new_inst(ByteCode::HALT),
+            Inst::new_data(ByteCode::LOAD, 0),
+            Inst::new_data(ByteCode::LOAD, 1),
+            Inst::new_inst(ByteCode::ADD),
+            Inst::new_inst(ByteCode::RET),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run().is_ok(), true);
+        assert_eq!(&[7].to_vec(), vm.debug_stack());
+    }
+
+    #[test]
+    fn test_call_recursive_sum_unwinds_frames_and_mem() {
+        // sum(n) = n == 0 ? 0 : n + sum(n - 1), called as sum(3).
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 3), // 0
+            Inst::new_call(3, 1),               // 1: sum(3)
+            Inst::new_inst(ByteCode::HALT),     // 2
+            Inst::new_data(ByteCode::LOAD, 0),  // 3: sum: push n
+            Inst::new_data(ByteCode::JMPZ, 12), // 4: if n == 0, goto base case
+            Inst::new_data(ByteCode::LOAD, 0),  // 5: push n (kept for the add)
+            Inst::new_data(ByteCode::LOADC, 1), // 6
+            Inst::new_data(ByteCode::LOAD, 0),  // 7: push n (for the subtract)
+            Inst::new_inst(ByteCode::SUB),      // 8: n - 1
+            Inst::new_call(3, 1),               // 9: sum(n - 1)
+            Inst::new_inst(ByteCode::ADD),      // 10: n + sum(n - 1)
+            Inst::new_inst(ByteCode::RET),      // 11
+            Inst::new_data(ByteCode::LOADC, 0), // 12: base case
+            Inst::new_inst(ByteCode::RET),      // 13
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert!(vm.run().is_ok());
+        assert_eq!(&[6].to_vec(), vm.debug_stack());
+        assert!(vm.debug_mem().is_empty());
+        assert!(vm.debug_frames().is_empty());
+    }
+
+    #[test]
+    fn test_call_missing_argc_traps() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::CALL, 0)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::MissingArgc { pc: 0 }));
+    }
+
+    #[test]
+    fn test_ret_without_call_traps() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 1),
+            Inst::new_inst(ByteCode::RET),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::InvalidProgramCounter { pc: 1 }));
+    }
+
+    #[test]
+    fn test_ret_without_call_leaves_mem_and_fp_unchanged() {
+        let instructions: Vec<Inst<i64>> = vec![
+            Inst::new_data(ByteCode::LOADC, 42),
+            Inst::new_data(ByteCode::STORE, 3),
+            Inst::new_data(ByteCode::LOADC, 7),
+            Inst::new_inst(ByteCode::RET),
+        ];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        assert_eq!(vm.run(), Err(VmError::InvalidProgramCounter { pc: 3 }));
+        assert_eq!(&[0, 0, 0, 42].to_vec(), vm.debug_mem());
+        assert_eq!(0, vm.debug_fp());
+    }
+
+    #[test]
+    fn test_budget_exhausted() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::JMP, 0)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        vm.set_budget(5);
+        assert_eq!(vm.run(), Err(VmError::BudgetExhausted { pc: 0 }));
+        assert_eq!(vm.cycles(), 6);
+    }
+
+    #[test]
+    fn test_timer_halts_execution() {
+        let instructions: Vec<Inst<i64>> = vec![Inst::new_data(ByteCode::JMP, 0)];
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(instructions);
+        vm.set_timer(2, |_vm: &VirtualMachine<i64>| true);
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.cycles(), 2);
+    }
+}
@@ -0,0 +1,157 @@
+//! A small line-oriented REPL for stepping `Inst<i64>` programs one
+//! instruction at a time.
+//!
+//! Each line is either a command (`mem`, `pc`, `reset`, `quit`) or an
+//! instruction in the form `OPCODE [operand] [argc]` (e.g. `LOADC 2`,
+//! `CALL 4 2`, `HALT`). Each instruction is appended to the machine's program
+//! and one `VirtualMachine::step` is taken, after which the operand stack is
+//! printed with `debug_stack`. The step runs the instruction at the current
+//! `pc`, which is the one just appended unless a jump, call, return or
+//! `reset` has moved `pc` elsewhere in the program.
+
+use std::io::{self, BufRead, Write};
+
+use super::bytecode::{ByteCode, Inst};
+use super::{StepOutcome, VirtualMachine};
+
+/// Run the REPL, reading lines from `input` and writing prompts and output
+/// to `output`, until `input` is exhausted or a `quit` command is read.
+pub fn run(
+    vm: &mut VirtualMachine<i64>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match line {
+            "mem" => writeln!(output, "{:?}", vm.debug_mem())?,
+            "pc" => writeln!(output, "{}", vm.debug_pc())?,
+            "reset" => {
+                vm.reset();
+                writeln!(output, "reset")?;
+            }
+            "quit" | "exit" => break,
+            _ => match parse_inst(line) {
+                Ok(inst) => {
+                    vm.push_inst(inst);
+                    match vm.step() {
+                        Ok(StepOutcome::Continue) => {}
+                        Ok(StepOutcome::Halted) => writeln!(output, "halted")?,
+                        Err(e) => writeln!(output, "trap: {}", e)?,
+                    }
+                    writeln!(output, "{:?}", vm.debug_stack())?;
+                }
+                Err(e) => writeln!(output, "{}", e)?,
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse one line of the REPL's tiny instruction syntax into an `Inst<i64>`.
+fn parse_inst(line: &str) -> Result<Inst<i64>, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or("empty instruction")?;
+    let opcode = opcode_from_name(name).ok_or_else(|| format!("unknown opcode {}", name))?;
+
+    if opcode == ByteCode::CALL {
+        let target = next_i64(&mut words, "CALL target")?;
+        let argc = next_i64(&mut words, "CALL argc")?;
+        Ok(Inst::new_call(target, argc as usize))
+    } else if opcode.has_operand() {
+        let data = next_i64(&mut words, "operand")?;
+        Ok(Inst::new_data(opcode, data))
+    } else {
+        Ok(Inst::new_inst(opcode))
+    }
+}
+
+fn next_i64<'a>(words: &mut impl Iterator<Item = &'a str>, what: &str) -> Result<i64, String> {
+    words
+        .next()
+        .ok_or_else(|| format!("missing {}", what))?
+        .parse()
+        .map_err(|_| format!("invalid {}", what))
+}
+
+fn opcode_from_name(name: &str) -> Option<ByteCode> {
+    match name.to_ascii_uppercase().as_str() {
+        "HALT" => Some(ByteCode::HALT),
+        "LOADC" => Some(ByteCode::LOADC),
+        "ADD" => Some(ByteCode::ADD),
+        "SUB" => Some(ByteCode::SUB),
+        "MUL" => Some(ByteCode::MUL),
+        "DIV" => Some(ByteCode::DIV),
+        "MOD" => Some(ByteCode::MOD),
+        "AND" => Some(ByteCode::AND),
+        "OR" => Some(ByteCode::OR),
+        "EQ" => Some(ByteCode::EQ),
+        "NEQ" => Some(ByteCode::NEQ),
+        "LE" => Some(ByteCode::LE),
+        "LEQ" => Some(ByteCode::LEQ),
+        "GE" => Some(ByteCode::GE),
+        "GEQ" => Some(ByteCode::GEQ),
+        "NEG" => Some(ByteCode::NEG),
+        "NOT" => Some(ByteCode::NOT),
+        "JMP" => Some(ByteCode::JMP),
+        "JMPZ" => Some(ByteCode::JMPZ),
+        "JMPNZ" => Some(ByteCode::JMPNZ),
+        "CALL" => Some(ByteCode::CALL),
+        "RET" => Some(ByteCode::RET),
+        "LOAD" => Some(ByteCode::LOAD),
+        "STORE" => Some(ByteCode::STORE),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_lines(vm: &mut VirtualMachine<i64>, lines: &str) -> String {
+        let mut out = Vec::new();
+        run(vm, lines.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn steps_one_instruction_per_line() {
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(Vec::new());
+        let out = run_lines(&mut vm, "LOADC 2\nLOADC 3\nADD\n");
+        assert_eq!(out, "[2]\n[2, 3]\n[5]\n");
+    }
+
+    #[test]
+    fn halt_is_reported() {
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(Vec::new());
+        let out = run_lines(&mut vm, "HALT\n");
+        assert_eq!(out, "halted\n[]\n");
+    }
+
+    #[test]
+    fn reports_traps_without_stopping_the_repl() {
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(Vec::new());
+        let out = run_lines(&mut vm, "ADD\nLOADC 1\n");
+        assert_eq!(out, "trap: stack underflow at pc 0\n[]\n[1]\n");
+    }
+
+    #[test]
+    fn mem_pc_and_reset_commands() {
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(Vec::new());
+        let out = run_lines(&mut vm, "LOADC 9\nSTORE 0\nmem\npc\nreset\npc\n");
+        assert_eq!(out, "[9]\n[]\n[9]\n2\nreset\n0\n");
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let mut vm: VirtualMachine<i64> = VirtualMachine::new(Vec::new());
+        let out = run_lines(&mut vm, "NOPE\n");
+        assert_eq!(out, "unknown opcode NOPE\n");
+    }
+}